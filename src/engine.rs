@@ -1,15 +1,18 @@
-// what will hopefully be an FM synthesis engine in Rust
+// a two-operator FM synthesis engine in Rust
 
 use midir::{Ignore, MidiInput, MidiInputConnection};
-use rodio::{
-    OutputStream,
-    source::{SineWave, Skippable, Source},
-};
+use midly::{MidiMessage, live::LiveEvent};
+use rodio::{OutputStream, Source};
 use std::{
     collections::HashMap,
     error::Error,
+    f64::consts::TAU,
     fmt,
-    sync::mpsc::{self, Sender},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
     thread,
     time::Duration,
 };
@@ -27,9 +30,129 @@ pub fn frequency(note: Note) -> f64 {
     440.0 * ((note as i16 - 69) as f64 / 12.0).exp2()
 }
 
-#[derive(Default)]
+/// Snapshot of the parameters a voice is built from: carrier/modulator ratio,
+/// peak modulation index and ADSR timing. Copied into [`Inner`] when the
+/// engine connects, and later mirrored there live as params gain a control
+/// path.
+#[derive(Debug, Clone, Copy)]
+struct VoiceParams {
+    master_volume: f32,
+    ratio: f32,
+    index: f32,
+    attack: Duration,
+    decay: Duration,
+    sustain: f32,
+    release: Duration,
+}
+
+impl Default for VoiceParams {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.0,
+            ratio: 2.0,
+            index: 5.0,
+            attack: Duration::from_millis(10),
+            decay: Duration::from_millis(120),
+            sustain: 0.7,
+            release: Duration::from_millis(250),
+        }
+    }
+}
+
+/// A synth-wide parameter a MIDI control-change can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SynthParam {
+    MasterVolume,
+    Ratio,
+    Index,
+    AttackTime,
+    ReleaseTime,
+}
+
+const ALL_SYNTH_PARAMS: [SynthParam; 5] = [
+    SynthParam::MasterVolume,
+    SynthParam::Ratio,
+    SynthParam::Index,
+    SynthParam::AttackTime,
+    SynthParam::ReleaseTime,
+];
+
+impl SynthParam {
+    /// Cycle to the next target in the fixed listing order, wrapping
+    /// around; used to rebind a CC controller from the UI.
+    pub fn next(&self) -> SynthParam {
+        let i = ALL_SYNTH_PARAMS.iter().position(|p| p == self).unwrap();
+        ALL_SYNTH_PARAMS[(i + 1) % ALL_SYNTH_PARAMS.len()]
+    }
+
+    /// Map a raw 0-127 CC value into this parameter's real range and apply
+    /// it.
+    fn apply(&self, params: &mut VoiceParams, raw: Velocity) {
+        let t = raw as f32 / MAX_VELOCITY as f32;
+        match self {
+            SynthParam::MasterVolume => params.master_volume = t * 1.2,
+            SynthParam::Ratio => params.ratio = 0.1 + t * (8.0 - 0.1),
+            SynthParam::Index => params.index = t * 20.0,
+            SynthParam::AttackTime => {
+                params.attack = Duration::from_millis(1 + (t * 1999.0) as u64)
+            }
+            SynthParam::ReleaseTime => {
+                params.release = Duration::from_millis(1 + (t * 2999.0) as u64)
+            }
+        }
+    }
+}
+
+/// Maps CC controller numbers to the [`SynthParam`] they drive; shared
+/// between [`Synth`] (for rebinding from the UI) and the audio thread (for
+/// applying incoming control changes).
+type CcMap = Arc<Mutex<HashMap<u8, SynthParam>>>;
+
+fn default_cc_map() -> CcMap {
+    // mod wheel -> modulation index
+    Arc::new(Mutex::new(HashMap::from([(1, SynthParam::Index)])))
+}
+
 pub struct Synth {
     connection: Option<MidiInputConnection<()>>,
+    event_tx: Option<Sender<Event>>,
+
+    /// Note-on events received from the physical MIDI input, separate from
+    /// `event_tx` so the UI can observe real velocities without stealing
+    /// events from the audio thread.
+    note_feedback_rx: Option<Receiver<(Note, Velocity)>>,
+
+    /// Only respond to MIDI messages on this channel (0-15); `None` listens
+    /// on every channel.
+    pub channel_filter: Option<u8>,
+
+    /// Maximum number of voices sounding at once; the oldest-triggered
+    /// voice is stolen to make room once the pool is full.
+    pub max_voices: usize,
+
+    /// CC controller number -> synth parameter, consulted live by the
+    /// audio thread on every incoming control change.
+    cc_map: CcMap,
+
+    /// Ratio, index and ADSR timing voices are built from. Mirrored here
+    /// pre-connect so `connect()` has something to seed [`Inner`] with, and
+    /// kept live afterwards: every `set_*` method below pushes the updated
+    /// snapshot to the audio thread the same way a CC-bound change does.
+    params: VoiceParams,
+}
+
+impl Default for Synth {
+    fn default() -> Self {
+        Self {
+            connection: None,
+            event_tx: None,
+            note_feedback_rx: None,
+            channel_filter: None,
+            max_voices: 8,
+            cc_map: default_cc_map(),
+            params: VoiceParams::default(),
+        }
+    }
 }
 
 impl fmt::Debug for Synth {
@@ -38,7 +161,147 @@ impl fmt::Debug for Synth {
     }
 }
 
+/// A decoded synth event, fed into the audio thread either from a MIDI
+/// input callback or directly from the UI.
+enum Event {
+    NoteOn(Note, Velocity),
+    NoteOff(Note),
+    ControlChange { controller: u8, value: u8 },
+    /// A direct (non-CC) change to the voice parameters, e.g. from
+    /// `Synth::set_ratio`; takes effect for voices triggered from this
+    /// point on, same as a CC-bound change.
+    SetVoiceParams(VoiceParams),
+    // pitch bend, aftertouch and program change have no synth-side target
+    // yet, so only decode that they happened, not their payload.
+    PitchBend,
+    ChannelAftertouch,
+    PolyAftertouch,
+    ProgramChange,
+}
+
+/// Decode a raw MIDI message into an [`Event`], dropping anything outside
+/// `channel_filter` (when set) and anything that isn't a channel voice
+/// message (sysex, realtime, ...).
+fn decode_event(message: &[u8], channel_filter: Option<u8>) -> Option<Event> {
+    let LiveEvent::Midi { channel, message } = LiveEvent::parse(message).ok()? else {
+        return None;
+    };
+
+    if let Some(wanted) = channel_filter {
+        if channel.as_int() != wanted {
+            return None;
+        }
+    }
+
+    Some(match message {
+        MidiMessage::NoteOn { key, vel } if vel.as_int() == 0 => Event::NoteOff(key.as_int()),
+        MidiMessage::NoteOn { key, vel } => Event::NoteOn(key.as_int(), vel.as_int()),
+        MidiMessage::NoteOff { key, .. } => Event::NoteOff(key.as_int()),
+        MidiMessage::Controller { controller, value } => Event::ControlChange {
+            controller: controller.as_int(),
+            value: value.as_int(),
+        },
+        MidiMessage::PitchBend { .. } => Event::PitchBend,
+        MidiMessage::ChannelAftertouch { .. } => Event::ChannelAftertouch,
+        MidiMessage::Aftertouch { .. } => Event::PolyAftertouch,
+        MidiMessage::ProgramChange { .. } => Event::ProgramChange,
+    })
+}
+
 impl Synth {
+    /// Apply `f` to the local parameter snapshot, then push the result to
+    /// the audio thread if it's already running; mirrors how a CC-bound
+    /// change reaches [`Inner`], just from a direct setter instead of a
+    /// control change.
+    fn set_params(&mut self, f: impl FnOnce(&mut VoiceParams)) {
+        f(&mut self.params);
+        if let Some(tx) = &self.event_tx {
+            tx.send(Event::SetVoiceParams(self.params))
+                .unwrap_or_default();
+        }
+    }
+
+    /// Gain applied to every voice on top of its own velocity.
+    pub fn set_master_volume(&mut self, master_volume: f32) {
+        self.set_params(|p| p.master_volume = master_volume);
+    }
+
+    /// Carrier:modulator frequency ratio, e.g. `2.0` gives `fm = 2*fc`.
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.set_params(|p| p.ratio = ratio);
+    }
+
+    /// Peak modulation index `I`, scaled down by velocity and the envelope.
+    pub fn set_index(&mut self, index: f32) {
+        self.set_params(|p| p.index = index);
+    }
+
+    /// Time for the envelope to rise from silence to peak.
+    pub fn set_attack(&mut self, attack: Duration) {
+        self.set_params(|p| p.attack = attack);
+    }
+
+    /// Time for the envelope to fall from peak to the sustain level.
+    pub fn set_decay(&mut self, decay: Duration) {
+        self.set_params(|p| p.decay = decay);
+    }
+
+    /// Level the envelope holds at while a note stays held, `0.0..=1.0`.
+    pub fn set_sustain(&mut self, sustain: f32) {
+        self.set_params(|p| p.sustain = sustain);
+    }
+
+    /// Time for the envelope to fall from its held level to silence once
+    /// the note is stopped.
+    pub fn set_release(&mut self, release: Duration) {
+        self.set_params(|p| p.release = release);
+    }
+
+    /// Bind `controller` to drive `param` on the next control change
+    /// received for it.
+    pub fn bind_cc(&mut self, controller: u8, param: SynthParam) {
+        self.cc_map.lock().unwrap().insert(controller, param);
+    }
+
+    /// The current CC bindings, sorted by controller number, for display.
+    pub fn cc_bindings(&self) -> Vec<(u8, SynthParam)> {
+        let mut bindings: Vec<_> = self
+            .cc_map
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&controller, &param)| (controller, param))
+            .collect();
+        bindings.sort_by_key(|(controller, _)| *controller);
+        bindings
+    }
+
+    /// Drain the note-on events received from the physical MIDI input since
+    /// the last call, with their real velocities, for velocity-sensitive UI
+    /// feedback (grid hits triggered from the keyboard have no physical
+    /// velocity to report and don't appear here).
+    pub fn poll_note_feedback(&mut self) -> Vec<(Note, Velocity)> {
+        self.note_feedback_rx
+            .as_ref()
+            .map(|rx| rx.try_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Trigger `note` directly, bypassing MIDI input (used by the on-screen
+    /// grid).
+    pub fn play(&mut self, note: Note, velocity: Velocity) {
+        if let Some(tx) = &self.event_tx {
+            tx.send(Event::NoteOn(note, velocity)).unwrap_or_default();
+        }
+    }
+
+    /// Release `note`, starting its voice's ADSR release stage.
+    pub fn stop(&mut self, note: Note) {
+        if let Some(tx) = &self.event_tx {
+            tx.send(Event::NoteOff(note)).unwrap_or_default();
+        }
+    }
+
     pub fn connect(&mut self) -> Result<(), Box<dyn Error>> {
         // midi input port
         // TODO refactor out into separate method
@@ -65,46 +328,53 @@ impl Synth {
         };
 
         let (tx, rx) = mpsc::channel();
+        let midi_tx = tx.clone();
+        let (feedback_tx, feedback_rx) = mpsc::channel();
+        let channel_filter = self.channel_filter;
 
         self.connection = Some(midi_in.connect(
             in_port,
             "midir-read-input",
-            // move |stamp, message, _| {
-            //     const NOTE_ON_MSG: u8 = 0x90;
-            //     const NOTE_OFF_MSG: u8 = 0x80;
-            //     println!("got {}: {:?} (len={})", stamp, message, message.len());
-            //     match message {
-            //         [NOTE_ON_MSG, note, velocity] => {
-            //             // inner.play(*note);
-            //             println!("note");
-            //         }
-            //         _ => println!("something else!"),
-            //     }
-            // },
-            move |stamp, message, _| {
-                tx.send((stamp, message.to_vec())).unwrap();
+            move |_stamp, message, _| {
+                if let Some(event) = decode_event(message, channel_filter) {
+                    if let Event::NoteOn(note, velocity) = &event {
+                        feedback_tx.send((*note, *velocity)).unwrap_or_default();
+                    }
+                    midi_tx.send(event).unwrap_or_default();
+                }
             },
             (),
         )?);
 
+        self.event_tx = Some(tx);
+        self.note_feedback_rx = Some(feedback_rx);
+        let params = self.params;
+        let max_voices = self.max_voices;
+        let cc_map = self.cc_map.clone();
+
         thread::spawn(move || {
             // audio stream
-            let mut inner = Inner::default();
+            let mut inner = Inner::new(params, max_voices);
             inner.stream = Some(
                 rodio::OutputStreamBuilder::open_default_stream().expect("open default stream"),
             );
-            // let sink = rodio::Sink::connect_new(&self.stream.mixer());
-
-            for (stamp, message) in rx {
-                const NOTE_ON_MSG: u8 = 0x90;
-                const NOTE_OFF_MSG: u8 = 0x80;
-                // println!("got {}: {:?} (len={})", stamp, message, message.len());
-                match message[..] {
-                    [NOTE_ON_MSG, note, 0] => inner.stop(note),
-                    [NOTE_ON_MSG, note, velocity] => inner.play(note, velocity),
-                    [NOTE_OFF_MSG, note, _velocity] => inner.stop(note),
-                    // _ => println!("something else!"),
-                    _ => {}
+
+            for event in rx {
+                match event {
+                    Event::NoteOn(note, velocity) => inner.play(note, velocity),
+                    Event::NoteOff(note) => inner.stop(note),
+                    Event::ControlChange { controller, value } => {
+                        if let Some(param) = cc_map.lock().unwrap().get(&controller).copied() {
+                            inner.set_param(param, value);
+                        }
+                    }
+                    Event::SetVoiceParams(params) => inner.params = params,
+                    // pitch bend, aftertouch and program change have no
+                    // synth-side target yet
+                    Event::PitchBend
+                    | Event::ChannelAftertouch
+                    | Event::PolyAftertouch
+                    | Event::ProgramChange => {}
                 }
             }
         });
@@ -113,41 +383,306 @@ impl Synth {
     }
 }
 
-#[derive(Default)]
+/// A note still physically held down, whether or not it currently owns a
+/// sounding voice.
+#[derive(Debug, Clone, Copy)]
+struct Held {
+    trigger: u64,
+    velocity: Velocity,
+}
+
 struct Inner {
     stream: Option<OutputStream>,
-    sources: HashMap<Note, Sender<()>>,
+    params: VoiceParams,
+    max_voices: usize,
+
+    /// Monotonic counter, incremented on every note-on; doubles as each
+    /// voice's "trigger time" for oldest-first stealing.
+    clock: u64,
+    /// Every currently-held key, indexed by note number, regardless of
+    /// whether it presently owns a voice.
+    held: [Option<Held>; 128],
+    /// Notes that currently own a voice in the pool.
+    active: HashMap<Note, (u64, Arc<AtomicBool>)>,
 }
 
 impl Inner {
+    fn new(params: VoiceParams, max_voices: usize) -> Self {
+        Self {
+            stream: None,
+            params,
+            max_voices,
+            clock: 0,
+            held: [None; 128],
+            active: HashMap::new(),
+        }
+    }
+
     fn play(&mut self, note: Note, velocity: Velocity) {
-        let (tx, rx) = mpsc::channel();
+        self.clock += 1;
+        let trigger = self.clock;
+        self.held[note as usize] = Some(Held { trigger, velocity });
 
-        if let Some(old_tx) = self.sources.insert(note, tx) {
-            // I don't know if we need this?
-            old_tx.send(()).unwrap_or_default();
-        };
+        if let Some((_, released)) = self.active.remove(&note) {
+            // retriggering an already-sounding note releases the old voice
+            // and reuses its slot rather than taking a second one
+            released.store(true, Ordering::Relaxed);
+        } else if self.active.len() >= self.max_voices {
+            self.steal_oldest();
+        }
 
-        let source = SineWave::new(frequency(note) as f32)
-            // .take_duration(Duration::from_secs_f32(0.25))
-            .amplify_normalized(velocity as f32 / MAX_VELOCITY as f32)
-            .skippable()
-            .periodic_access(Duration::from_micros(100), move |s| {
-                if let Ok(_) = rx.try_recv() {
-                    Skippable::skip(s);
-                }
-            });
+        self.spawn_voice(note, velocity, trigger);
+    }
+
+    fn stop(&mut self, note: Note) {
+        self.held[note as usize] = None;
+        if let Some((_, released)) = self.active.remove(&note) {
+            released.store(true, Ordering::Relaxed);
+        }
+        self.reallocate_from_held();
+    }
+
+    /// Apply a CC-driven parameter change; takes effect for voices
+    /// triggered from this point on.
+    fn set_param(&mut self, param: SynthParam, raw: Velocity) {
+        param.apply(&mut self.params, raw);
+    }
+
+    /// Release the voice with the oldest trigger time, freeing a pool slot.
+    fn steal_oldest(&mut self) {
+        let oldest = self
+            .active
+            .iter()
+            .min_by_key(|(_, (trigger, _))| *trigger)
+            .map(|(&note, _)| note);
+
+        if let Some(note) = oldest {
+            if let Some((_, released)) = self.active.remove(&note) {
+                released.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// When a slot frees up, give it to the most recently triggered held
+    /// note that doesn't already have a voice (e.g. one stolen earlier
+    /// while still held), so releasing notes out of order still resolves
+    /// correctly.
+    fn reallocate_from_held(&mut self) {
+        if self.active.len() >= self.max_voices {
+            return;
+        }
+
+        if let Some((note, held)) = self.reallocation_candidate() {
+            self.spawn_voice(note, held.velocity, held.trigger);
+        }
+    }
+
+    /// The held note, if any, that `reallocate_from_held` would give the
+    /// next free slot to: the most recently triggered held note that
+    /// doesn't already own a voice.
+    fn reallocation_candidate(&self) -> Option<(Note, Held)> {
+        self.held
+            .iter()
+            .enumerate()
+            .filter_map(|(note, held)| held.map(|held| (note as Note, held)))
+            .filter(|(note, _)| !self.active.contains_key(note))
+            .max_by_key(|(_, held)| held.trigger)
+    }
+
+    fn spawn_voice(&mut self, note: Note, velocity: Velocity, trigger: u64) {
+        let released = Arc::new(AtomicBool::new(false));
+
+        let sample_rate = self
+            .stream
+            .as_ref()
+            .expect("is initialized")
+            .config()
+            .sample_rate();
+
+        let voice = FmVoice::new(note, velocity, self.params, sample_rate, released.clone());
         self.stream
             .as_mut()
             .expect("is initialized")
             .mixer()
-            .add(source);
+            .add(voice);
+
+        self.active.insert(note, (trigger, released));
     }
+}
 
-    fn stop(&mut self, note: Note) {
-        if let Some(tx) = self.sources.get(&note) {
-            tx.send(()).unwrap();
-        };
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EnvelopeStage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Done,
+}
+
+/// A two-operator FM voice: a carrier oscillator phase-modulated by a
+/// modulator oscillator, `sin(2*pi*fc*t + I*sin(2*pi*fm*t))`, with `I` and
+/// the output gain driven by a per-sample ADSR envelope.
+struct FmVoice {
+    sample_rate: u32,
+    sample_idx: u64,
+
+    fc: f64,
+    fm: f64,
+
+    gain: f32,
+    index: f32,
+
+    attack_samples: u64,
+    decay_samples: u64,
+    sustain_level: f32,
+    release_samples: u64,
+
+    stage: EnvelopeStage,
+    stage_start: u64,
+    release_start_level: f32,
+
+    released: Arc<AtomicBool>,
+}
+
+impl FmVoice {
+    fn new(
+        note: Note,
+        velocity: Velocity,
+        params: VoiceParams,
+        sample_rate: u32,
+        released: Arc<AtomicBool>,
+    ) -> Self {
+        let fc = frequency(note);
+        let velocity_scale = velocity as f32 / MAX_VELOCITY as f32;
+
+        Self {
+            sample_rate,
+            sample_idx: 0,
+
+            fc,
+            fm: params.ratio as f64 * fc,
+
+            gain: velocity_scale * params.master_volume,
+            index: params.index * velocity_scale,
+
+            attack_samples: samples_for(params.attack, sample_rate),
+            decay_samples: samples_for(params.decay, sample_rate),
+            sustain_level: params.sustain,
+            release_samples: samples_for(params.release, sample_rate).max(1),
+
+            stage: EnvelopeStage::Attack,
+            stage_start: 0,
+            release_start_level: 0.0,
+
+            released,
+        }
+    }
+
+    /// Advance the envelope state machine and return its current level,
+    /// `0.0..=1.0`.
+    fn envelope_level(&mut self) -> f32 {
+        let elapsed = self.sample_idx - self.stage_start;
+
+        if self.stage != EnvelopeStage::Release
+            && self.stage != EnvelopeStage::Done
+            && self.released.load(Ordering::Relaxed)
+        {
+            self.release_start_level = self.level_within_stage(elapsed);
+            self.stage = EnvelopeStage::Release;
+            self.stage_start = self.sample_idx;
+            return self.release_start_level;
+        }
+
+        let elapsed = self.sample_idx - self.stage_start;
+        match self.stage {
+            EnvelopeStage::Attack => {
+                if elapsed >= self.attack_samples {
+                    self.stage = EnvelopeStage::Decay;
+                    self.stage_start = self.sample_idx;
+                    self.envelope_level()
+                } else {
+                    ramp(0.0, 1.0, elapsed, self.attack_samples)
+                }
+            }
+            EnvelopeStage::Decay => {
+                if elapsed >= self.decay_samples {
+                    self.stage = EnvelopeStage::Sustain;
+                    self.stage_start = self.sample_idx;
+                    self.envelope_level()
+                } else {
+                    ramp(1.0, self.sustain_level, elapsed, self.decay_samples)
+                }
+            }
+            EnvelopeStage::Sustain => self.sustain_level,
+            EnvelopeStage::Release => {
+                if elapsed >= self.release_samples {
+                    self.stage = EnvelopeStage::Done;
+                    0.0
+                } else {
+                    ramp(self.release_start_level, 0.0, elapsed, self.release_samples)
+                }
+            }
+            EnvelopeStage::Done => 0.0,
+        }
+    }
+
+    fn level_within_stage(&self, elapsed: u64) -> f32 {
+        match self.stage {
+            EnvelopeStage::Attack => ramp(0.0, 1.0, elapsed, self.attack_samples),
+            EnvelopeStage::Decay => ramp(1.0, self.sustain_level, elapsed, self.decay_samples),
+            EnvelopeStage::Sustain => self.sustain_level,
+            EnvelopeStage::Release => ramp(self.release_start_level, 0.0, elapsed, self.release_samples),
+            EnvelopeStage::Done => 0.0,
+        }
+    }
+}
+
+fn ramp(from: f32, to: f32, elapsed: u64, total: u64) -> f32 {
+    if total == 0 {
+        return to;
+    }
+    let t = (elapsed as f32 / total as f32).clamp(0.0, 1.0);
+    from + (to - from) * t
+}
+
+fn samples_for(duration: Duration, sample_rate: u32) -> u64 {
+    (duration.as_secs_f64() * sample_rate as f64).round() as u64
+}
+
+impl Iterator for FmVoice {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let env = self.envelope_level();
+        if self.stage == EnvelopeStage::Done {
+            return None;
+        }
+
+        let t = self.sample_idx as f64 / self.sample_rate as f64;
+        let modulator = (TAU * self.fm * t).sin();
+        let modulation_index = self.index as f64 * env as f64;
+        let carrier = (TAU * self.fc * t + modulation_index * modulator).sin();
+
+        self.sample_idx += 1;
+        Some((carrier * self.gain as f64 * env as f64) as f32)
+    }
+}
+
+impl Source for FmVoice {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
     }
 }
 
@@ -162,4 +697,86 @@ mod test {
         assert!(approx_eq!(f64, frequency(127), 12543.854, epsilon = 0.0001));
         assert!(approx_eq!(f64, frequency(69), 440.0, epsilon = 0.0001));
     }
+
+    #[test]
+    fn test_envelope_attacks_from_silence() {
+        let voice = FmVoice::new(
+            69,
+            MAX_VELOCITY,
+            VoiceParams::default(),
+            48_000,
+            Arc::new(AtomicBool::new(false)),
+        );
+        assert_eq!(voice.stage, EnvelopeStage::Attack);
+        assert_eq!(voice.sample_idx, 0);
+    }
+
+    #[test]
+    fn test_release_ends_the_stream() {
+        let released = Arc::new(AtomicBool::new(true));
+        let mut voice = FmVoice::new(69, MAX_VELOCITY, VoiceParams::default(), 48_000, released);
+        // with the release flag set before the first sample is pulled, the
+        // voice goes straight to its release ramp, which for the default
+        // 250 ms at 48 kHz ends after 12,000 samples.
+        let mut samples = 0;
+        while voice.next().is_some() {
+            samples += 1;
+            assert!(samples < 48_000, "voice should self-terminate");
+        }
+    }
+
+    #[test]
+    fn test_steal_oldest_releases_the_oldest_voice() {
+        let mut inner = Inner::new(VoiceParams::default(), 2);
+        let old = Arc::new(AtomicBool::new(false));
+        let newer = Arc::new(AtomicBool::new(false));
+        inner.active.insert(60, (1, old.clone()));
+        inner.active.insert(64, (2, newer.clone()));
+
+        inner.steal_oldest();
+
+        assert!(old.load(Ordering::Relaxed), "oldest voice released");
+        assert!(!newer.load(Ordering::Relaxed), "newer voice untouched");
+        assert!(!inner.active.contains_key(&60));
+        assert!(inner.active.contains_key(&64));
+    }
+
+    #[test]
+    fn test_reallocation_candidate_prefers_most_recent_held_note_without_a_voice() {
+        let mut inner = Inner::new(VoiceParams::default(), 1);
+        inner.held[60] = Some(Held {
+            trigger: 1,
+            velocity: 100,
+        });
+        inner.held[64] = Some(Held {
+            trigger: 3,
+            velocity: 90,
+        });
+        // already has a voice, so it isn't a candidate even though its
+        // trigger time is the most recent after 64's
+        inner.held[67] = Some(Held {
+            trigger: 2,
+            velocity: 80,
+        });
+        inner.active.insert(67, (2, Arc::new(AtomicBool::new(false))));
+
+        let candidate = inner.reallocation_candidate().map(|(note, _)| note);
+
+        assert_eq!(candidate, Some(64));
+    }
+
+    #[test]
+    fn test_reallocate_from_held_noop_when_pool_still_full() {
+        let mut inner = Inner::new(VoiceParams::default(), 1);
+        inner.held[60] = Some(Held {
+            trigger: 1,
+            velocity: 100,
+        });
+        inner.active.insert(64, (2, Arc::new(AtomicBool::new(false))));
+
+        inner.reallocate_from_held();
+
+        assert!(!inner.active.contains_key(&60));
+        assert!(inner.active.contains_key(&64));
+    }
 }
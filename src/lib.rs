@@ -0,0 +1,3 @@
+pub mod engine;
+pub mod music;
+pub mod sequencer;
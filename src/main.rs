@@ -1,15 +1,18 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::time::Duration;
 
 use color_eyre::{Result, eyre::eyre};
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
-use midikitty::engine::Synth;
+use midikitty::engine::{Synth, SynthParam};
+use midikitty::music::{self, KeyMap};
+use midikitty::sequencer::{self, Sequencer};
 use ratatui::{
     DefaultTerminal, Frame,
     layout::{Direction, Layout},
     prelude::{Alignment, Buffer, Constraint, Rect},
     style::{Color, Stylize},
-    text::Line,
+    text::{Line, Span},
     widgets::{Block, Paragraph, Widget},
 };
 
@@ -28,11 +31,22 @@ struct Grid {
     active_cell: usize,
     pad_state: Vec<PadState>,
     pad_config: Vec<PadConfig>,
+    /// Note-name label for each pad, kept in sync with the active
+    /// [`KeyMap`].
+    labels: Vec<String>,
+    /// Whether each pad is the scale's root, rendered in a distinct hue
+    /// from the rest of the scale degrees.
+    roots: Vec<bool>,
 }
 
 #[derive(Debug, Default, Clone)]
 struct PadState {
-    active: bool,
+    /// Velocity of the most recent hit, scaling the highlight's peak
+    /// brightness.
+    last_velocity: u8,
+    /// Highlight brightness, `1.0` right after a hit, decaying to `0.0`
+    /// over subsequent frames rather than snapping off on the next press.
+    intensity: f32,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -41,14 +55,6 @@ struct PadConfig {
     velocity: u32,
 }
 
-// TODO: Make this just a keyboard layout, then subselect a portion
-// to use for the grid depeneding on the number of rows/columns
-const GRID_LETTERS: [[&str; 10]; 3] = [
-    ["q", "w", "e", "r", "t", "y", "u", "i", "o", "p"],
-    ["a", "w", "s", "d", "f", "g", "h", "j", "k", "l"],
-    ["z", "x", "c", "v", "b", "n", "m", "<", ">", "."],
-];
-
 impl Widget for &Grid {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let col_constraints = (0..self.cols).map(|_| Constraint::Length(9));
@@ -63,24 +69,26 @@ impl Widget for &Grid {
             let row = i / self.cols;
             let col = i % self.cols;
             let g = self.grid_index(row, col);
+            let pad = &self.pad_state[g];
 
-            if self.pad_state[g].active {
-                Paragraph::new(format!("HIT"))
-                    .alignment(Alignment::Center)
-                    .block(Block::bordered())
-                    .bg(Color::Green)
-                    .render(cell, buf);
-            } else {
-                Paragraph::new(format!("{}", GRID_LETTERS[row][col]))
-                    .alignment(Alignment::Center)
-                    .block(Block::bordered())
-                    .render(cell, buf);
-            }
+            let velocity_scale = pad.last_velocity as f32 / 127.0;
+            let brightness = pad.intensity * velocity_scale;
+
+            Paragraph::new(self.labels[g].clone())
+                .alignment(Alignment::Center)
+                .block(Block::bordered())
+                .bg(Grid::pad_color(self.roots[g], brightness))
+                .render(cell, buf);
         }
     }
 }
 
 impl Grid {
+    /// Brightness lost per render frame; fades a hit out over roughly
+    /// twenty frames of the main loop rather than cutting it off the
+    /// instant another pad is struck.
+    const DECAY_PER_FRAME: f32 = 0.05;
+
     pub fn new(rows: usize, cols: usize) -> Self {
         let mut app = Self::default();
 
@@ -88,6 +96,8 @@ impl Grid {
         app.cols = cols;
         app.pad_state = vec![PadState::default(); rows * cols];
         app.pad_config = vec![PadConfig::default(); rows * cols];
+        app.labels = vec![String::new(); rows * cols];
+        app.roots = vec![false; rows * cols];
 
         app
     }
@@ -96,18 +106,46 @@ impl Grid {
         row * self.cols + col
     }
 
-    fn play(&mut self, row: usize, col: usize) {
+    fn set_labels(&mut self, labels: Vec<String>) {
+        self.labels = labels;
+    }
+
+    /// Mark which pads sit on the scale's root, so they render in a
+    /// distinct hue from the rest of the scale degrees.
+    fn set_roots(&mut self, roots: Vec<bool>) {
+        self.roots = roots;
+    }
+
+    fn play(&mut self, row: usize, col: usize, velocity: u8) {
         let g = self.grid_index(row, col);
+        self.pad_state[g].last_velocity = velocity;
+        self.pad_state[g].intensity = 1.0;
+    }
 
-        // TODO: #5 Unset after some timeout instead of on press
-        for i in 0..(self.rows * self.cols) {
-            if i == g {
-                self.pad_state[i].active = true;
-            } else {
-                self.pad_state[i].active = false;
-            }
+    /// Fade every pad's highlight by one frame's worth of decay; called
+    /// once per trip around the main loop.
+    fn decay(&mut self) {
+        for pad in &mut self.pad_state {
+            pad.intensity = (pad.intensity - Self::DECAY_PER_FRAME).max(0.0);
         }
     }
+
+    /// Background color for a pad: root pads glow amber, the rest cyan,
+    /// both fading to black as `brightness` (intensity scaled by the
+    /// triggering velocity) decays to zero.
+    fn pad_color(is_root: bool, brightness: f32) -> Color {
+        let brightness = brightness.clamp(0.0, 1.0);
+        let (r, g, b) = if is_root {
+            (255.0, 191.0, 0.0)
+        } else {
+            (0.0, 200.0, 255.0)
+        };
+        Color::Rgb(
+            (r * brightness) as u8,
+            (g * brightness) as u8,
+            (b * brightness) as u8,
+        )
+    }
 }
 
 #[derive(Clone, PartialEq, Debug, Default)]
@@ -115,6 +153,7 @@ pub enum AppMode {
     #[default]
     MIDI,
     Synth,
+    Sequence,
     Edit,
 }
 
@@ -123,6 +162,7 @@ impl fmt::Display for AppMode {
         let mode_str = match self {
             AppMode::MIDI => "MIDI",
             AppMode::Synth => "Synth",
+            AppMode::Sequence => "Sequencer",
             AppMode::Edit => "Synth (EDITING)",
         };
         write!(f, "{}", mode_str)
@@ -143,6 +183,15 @@ pub struct MIDIKitty {
 
     keymap: HashMap<KeyCode, (usize, usize)>,
 
+    // Scale/root/octave mapping from grid degree to MIDI note
+    key_map: KeyMap,
+
+    /// CC controller number `cycle_cc_binding` targets in [`AppMode::Edit`],
+    /// steppable so any controller can be rebound, not just a fixed few.
+    selected_cc: u8,
+
+    sequencer: Sequencer,
+
     engine: Synth,
 }
 
@@ -181,6 +230,10 @@ impl MIDIKitty {
             (KeyCode::Char(','), (2, 7)),
         ]);
 
+        app.sequencer = Sequencer::new(app.grid.rows * app.grid.cols);
+        app.selected_cc = 1; // mod wheel, matches the default CC binding
+        app.refresh_labels();
+
         app
     }
 
@@ -190,8 +243,11 @@ impl MIDIKitty {
         self.connect()?;
 
         while self.running {
+            self.grid.decay();
             terminal.draw(|frame| self.render(frame))?;
             self.handle_crossterm_events()?;
+            self.handle_sequencer_ticks();
+            self.handle_midi_feedback();
         }
 
         Ok(())
@@ -213,9 +269,18 @@ impl MIDIKitty {
             .blue()
             .centered();
 
+        let constraints = if self.mode == AppMode::Sequence {
+            vec![
+                Constraint::Percentage(10),
+                Constraint::Length(3),
+                Constraint::Min(0),
+            ]
+        } else {
+            vec![Constraint::Percentage(10), Constraint::Percentage(90)]
+        };
         let layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints(vec![Constraint::Percentage(10), Constraint::Percentage(90)])
+            .constraints(constraints)
             .split(frame.area());
 
         frame.render_widget(title, layout[0]);
@@ -224,15 +289,72 @@ impl MIDIKitty {
             AppMode::MIDI | AppMode::Synth => {
                 frame.render_widget(&self.grid, layout[1]);
             }
-            AppMode::Edit => {}
+            AppMode::Sequence => {
+                frame.render_widget(self.sequencer_strip(), layout[1]);
+                frame.render_widget(&self.grid, layout[2]);
+            }
+            AppMode::Edit => {
+                let bindings = self
+                    .engine
+                    .cc_bindings()
+                    .iter()
+                    .map(|(cc, param)| format!("CC{cc}->{param:?}"))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+
+                let info = Paragraph::new(format!(
+                    "Scale: {:?}   Root: {}   Octave: {}\n\n\
+                     ←/→ root   ↑/↓ octave   [ / ] scale\n\n\
+                     CC bindings: {bindings}\n\
+                     Selected CC: {}   PgUp/PgDn select CC   Enter cycle its target",
+                    self.key_map.scale,
+                    music::pitch_class_name(self.key_map.root),
+                    self.key_map.octave,
+                    self.selected_cc,
+                ));
+                frame.render_widget(info, layout[1]);
+            }
         }
     }
 
+    /// A single-row strip showing the sequencer's transport state and, as a
+    /// moving playhead, the step the clock plays on its next tick; the step
+    /// sounding right now is the one before the marker.
+    fn sequencer_strip(&self) -> Paragraph<'static> {
+        let spans = (0..sequencer::PATTERN_LENGTH)
+            .map(|step| {
+                let marker = if self.sequencer.is_playing() && step == self.sequencer.playhead {
+                    "█ "
+                } else {
+                    "· "
+                };
+                Span::raw(marker)
+            })
+            .collect::<Vec<_>>();
+
+        let status = if self.sequencer.is_playing() {
+            "playing"
+        } else {
+            "stopped"
+        };
+        let recording = if self.sequencer.recording { ", rec" } else { "" };
+
+        Paragraph::new(Line::from(spans)).block(Block::bordered().title(format!(
+            "Sequencer [{status}{recording}] {:.0} BPM  (space play/stop, ^r record, ^x clear)",
+            self.sequencer.bpm
+        )))
+    }
+
     /// Reads the crossterm events and updates the state of [`App`].
     ///
-    /// If your application needs to perform work in between handling events, you can use the
-    /// [`event::poll`] function to check if there are any events available with a timeout.
+    /// Uses a short [`event::poll`] timeout rather than blocking on
+    /// [`event::read`] so the main loop keeps coming back around to drain
+    /// sequencer ticks even while idle at the keyboard.
     fn handle_crossterm_events(&mut self) -> Result<()> {
+        if !event::poll(Duration::from_millis(15))? {
+            return Ok(());
+        }
+
         match event::read()? {
             // it's important to check KeyEventKind::Press to avoid handling key release events
             Event::Key(key) if key.kind == KeyEventKind::Press => self.on_key_event(key),
@@ -243,28 +365,126 @@ impl MIDIKitty {
         Ok(())
     }
 
+    /// Drain a pending sequencer tick, releasing the previous step's notes
+    /// and triggering the current one through the same path as a live pad
+    /// hit.
+    fn handle_sequencer_ticks(&mut self) {
+        let Some(tick) = self.sequencer.poll() else {
+            return;
+        };
+
+        for pad in tick.note_offs {
+            let note = self.note_number(pad / self.grid.cols, pad % self.grid.cols);
+            self.engine.stop(note);
+        }
+        for pad in tick.note_ons {
+            self.trigger_pad(pad / self.grid.cols, pad % self.grid.cols);
+        }
+    }
+
+    /// Light up whichever pads the physical MIDI input just struck, with
+    /// their real velocity, so the grid's brightness actually varies with
+    /// playing dynamics rather than always showing the fixed pad velocity.
+    fn handle_midi_feedback(&mut self) {
+        for (note, velocity) in self.engine.poll_note_feedback() {
+            for row in 0..self.grid.rows {
+                for col in 0..self.grid.cols {
+                    if self.note_number(row, col) == note {
+                        self.grid.play(row, col, velocity);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Velocity used for grid pad hits, which have no physical key pressure
+    /// to derive one from.
+    const PAD_VELOCITY: u8 = 100;
+
+    /// Handle a live pad hit: trigger it and, while recording, write it into
+    /// the sequencer's current step.
     fn play_key(&mut self, row: usize, col: usize) {
-        self.grid.play(row, col);
-        self.engine.play(self.note_number(row, col));
+        self.trigger_pad(row, col);
+
+        if self.mode == AppMode::Sequence {
+            self.sequencer.record(row * self.grid.cols + col);
+        }
+    }
+
+    /// Light the pad and sound its note, without touching the sequencer.
+    /// Used both by live hits and by sequencer playback itself, so that
+    /// played-back notes are never re-recorded onto the following step.
+    fn trigger_pad(&mut self, row: usize, col: usize) {
+        self.grid.play(row, col, Self::PAD_VELOCITY);
+        self.engine
+            .play(self.note_number(row, col), Self::PAD_VELOCITY);
     }
 
     fn note_number(&self, row: usize, col: usize) -> u8 {
-        36 + (row * self.grid.cols + col) as u8
+        self.key_map.note_for_degree(row * self.grid.cols + col)
+    }
+
+    /// The highest scale degree any pad in the grid can produce, so
+    /// [`KeyMap`]'s octave clamp can be derived from the grid actually in
+    /// use rather than a fixed constant.
+    fn max_degree(&self) -> usize {
+        self.grid.rows * self.grid.cols - 1
+    }
+
+    /// Recompute the grid's pad labels and root highlighting from the
+    /// current [`KeyMap`].
+    fn refresh_labels(&mut self) {
+        let degrees = 0..self.grid.rows * self.grid.cols;
+        let scale_len = self.key_map.scale.intervals().len();
+
+        let labels = degrees
+            .clone()
+            .map(|degree| music::note_name(self.key_map.note_for_degree(degree)))
+            .collect();
+        let roots = degrees.map(|degree| degree % scale_len == 0).collect();
+
+        self.grid.set_labels(labels);
+        self.grid.set_roots(roots);
     }
 
     fn switch_mode(&mut self) {
         self.mode = match self.mode {
             AppMode::MIDI => AppMode::Synth,
-            AppMode::Synth => AppMode::MIDI,
+            AppMode::Synth => AppMode::Sequence,
+            AppMode::Sequence => AppMode::MIDI,
             AppMode::Edit => self.mode.clone(),
         }
     }
 
+    /// Cycle the [`SynthParam`] bound to `controller`, adding a binding to
+    /// [`SynthParam::MasterVolume`] if it isn't bound yet.
+    fn cycle_cc_binding(&mut self, controller: u8) {
+        let current = self
+            .engine
+            .cc_bindings()
+            .into_iter()
+            .find(|(cc, _)| *cc == controller)
+            .map(|(_, param)| param);
+
+        let next = match current {
+            Some(param) => param.next(),
+            None => SynthParam::MasterVolume,
+        };
+        self.engine.bind_cc(controller, next);
+    }
+
+    fn toggle_edit_mode(&mut self) {
+        self.mode = match self.mode {
+            AppMode::Edit => AppMode::MIDI,
+            AppMode::MIDI | AppMode::Synth | AppMode::Sequence => AppMode::Edit,
+        }
+    }
+
     /// Handles the key events and updates the state of [`App`].
     fn on_key_event(&mut self, key: KeyEvent) {
         let mapped_key = self.keymap.get(&key.code);
 
-        if key.modifiers.is_empty() && mapped_key.is_some() {
+        if self.mode != AppMode::Edit && key.modifiers.is_empty() && mapped_key.is_some() {
             let mapped_grid = mapped_key.unwrap();
             self.play_key(mapped_grid.0, mapped_grid.1);
         }
@@ -273,6 +493,64 @@ impl MIDIKitty {
             (_, KeyCode::Esc)
             | (KeyModifiers::CONTROL, KeyCode::Char('c') | KeyCode::Char('C')) => self.quit(),
             (_, KeyCode::Tab) => self.switch_mode(),
+            (KeyModifiers::CONTROL, KeyCode::Char('e') | KeyCode::Char('E')) => {
+                self.toggle_edit_mode()
+            }
+
+            (_, KeyCode::Left) if self.mode == AppMode::Edit => {
+                self.key_map.cycle_root(-1, self.max_degree());
+                self.refresh_labels();
+            }
+            (_, KeyCode::Right) if self.mode == AppMode::Edit => {
+                self.key_map.cycle_root(1, self.max_degree());
+                self.refresh_labels();
+            }
+            (_, KeyCode::Up) if self.mode == AppMode::Edit => {
+                self.key_map.shift_octave(1, self.max_degree());
+                self.refresh_labels();
+            }
+            (_, KeyCode::Down) if self.mode == AppMode::Edit => {
+                self.key_map.shift_octave(-1, self.max_degree());
+                self.refresh_labels();
+            }
+            (_, KeyCode::Char('[')) if self.mode == AppMode::Edit => {
+                self.key_map.cycle_scale_prev(self.max_degree());
+                self.refresh_labels();
+            }
+            (_, KeyCode::Char(']')) if self.mode == AppMode::Edit => {
+                self.key_map.cycle_scale_next(self.max_degree());
+                self.refresh_labels();
+            }
+            (_, KeyCode::PageUp) if self.mode == AppMode::Edit => {
+                self.selected_cc = self.selected_cc.saturating_add(1).min(127);
+            }
+            (_, KeyCode::PageDown) if self.mode == AppMode::Edit => {
+                self.selected_cc = self.selected_cc.saturating_sub(1);
+            }
+            (_, KeyCode::Enter) if self.mode == AppMode::Edit => {
+                self.cycle_cc_binding(self.selected_cc)
+            }
+
+            (_, KeyCode::Char(' ')) if self.mode == AppMode::Sequence => {
+                if self.sequencer.is_playing() {
+                    for pad in self.sequencer.stop() {
+                        let note = self.note_number(pad / self.grid.cols, pad % self.grid.cols);
+                        self.engine.stop(note);
+                    }
+                } else {
+                    self.sequencer.play();
+                }
+            }
+            (KeyModifiers::CONTROL, KeyCode::Char('r') | KeyCode::Char('R'))
+                if self.mode == AppMode::Sequence =>
+            {
+                self.sequencer.recording = !self.sequencer.recording;
+            }
+            (KeyModifiers::CONTROL, KeyCode::Char('x') | KeyCode::Char('X'))
+                if self.mode == AppMode::Sequence =>
+            {
+                self.sequencer.clear();
+            }
 
             // Add other key handlers here.
             _ => {}
@@ -0,0 +1,263 @@
+// scale- and root-aware mapping from grid degree to MIDI note
+
+/// Semitone intervals of a scale's degrees above its root, spanning one
+/// octave; degrees past the end wrap into the next octave up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scale {
+    #[default]
+    Major,
+    Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    Pentatonic,
+    Chromatic,
+}
+
+const ALL_SCALES: [Scale; 9] = [
+    Scale::Major,
+    Scale::Minor,
+    Scale::Dorian,
+    Scale::Phrygian,
+    Scale::Lydian,
+    Scale::Mixolydian,
+    Scale::Locrian,
+    Scale::Pentatonic,
+    Scale::Chromatic,
+];
+
+impl Scale {
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            Scale::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            Scale::Pentatonic => &[0, 2, 4, 7, 9],
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+        }
+    }
+
+    /// Cycle to the next scale in the fixed listing order, wrapping around.
+    pub fn next(&self) -> Scale {
+        let i = ALL_SCALES.iter().position(|s| s == self).unwrap();
+        ALL_SCALES[(i + 1) % ALL_SCALES.len()]
+    }
+
+    /// Cycle to the previous scale in the fixed listing order, wrapping
+    /// around.
+    pub fn prev(&self) -> Scale {
+        let i = ALL_SCALES.iter().position(|s| s == self).unwrap();
+        ALL_SCALES[(i + ALL_SCALES.len() - 1) % ALL_SCALES.len()]
+    }
+}
+
+const PITCH_CLASS_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Name a pitch class (`0..=11`, `0` = C), without an octave number.
+pub fn pitch_class_name(pitch_class: u8) -> &'static str {
+    PITCH_CLASS_NAMES[(pitch_class % 12) as usize]
+}
+
+/// Name a MIDI note, e.g. `60` -> `"C4"`.
+pub fn note_name(note: u8) -> String {
+    let octave = (note as i32) / 12 - 1;
+    format!("{}{}", pitch_class_name(note % 12), octave)
+}
+
+/// Maps an isomorphic grid of "scale degrees" onto MIDI notes: degree `0` is
+/// the root, and each subsequent degree climbs the scale, wrapping into
+/// higher octaves as it passes the top of the scale.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyMap {
+    pub scale: Scale,
+    /// Root pitch class, `0..=11` (`0` = C).
+    pub root: u8,
+    /// Octave offset from the default middle register; `0` puts the root
+    /// at the same note the original chromatic grid started on.
+    pub octave: i8,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self {
+            scale: Scale::Chromatic,
+            root: 0,
+            octave: 0,
+        }
+    }
+}
+
+impl KeyMap {
+    /// The MIDI note for scale `degree` (`0`-based) above the root.
+    pub fn note_for_degree(&self, degree: usize) -> u8 {
+        let semitone = self.semitone_for_degree(degree);
+        (self.base_note() as i32 + semitone).clamp(0, 127) as u8
+    }
+
+    /// Semitone offset of `degree` above the root, wrapping into higher
+    /// octaves as it passes the top of the scale. Non-decreasing in
+    /// `degree`: each wrap resets to the scale's `0` interval but adds a
+    /// full octave, which is always more than the partial octave it
+    /// replaces.
+    fn semitone_for_degree(&self, degree: usize) -> i32 {
+        let intervals = self.scale.intervals();
+        let octave_offset = (degree / intervals.len()) as i32 * 12;
+        intervals[degree % intervals.len()] as i32 + octave_offset
+    }
+
+    /// The note the root sits on, before applying scale degrees.
+    fn base_note(&self) -> u8 {
+        (((self.octave as i32) + 3) * 12 + self.root as i32).clamp(0, 127) as u8
+    }
+
+    /// The inclusive octave range for which every degree in
+    /// `0..=max_degree` maps to a distinct MIDI note rather than saturating
+    /// against `0` or `127`. Degree `0` always sits on the root itself
+    /// (`0..=11`), so the low end is always safely within range; only the
+    /// high end depends on the scale and the grid's degree span.
+    fn octave_bounds(&self, max_degree: usize) -> (i8, i8) {
+        let headroom = 127 - self.semitone_for_degree(max_degree) - self.root as i32;
+        let max_octave = (headroom.div_euclid(12) - 3).clamp(-3, 5) as i8;
+        (-3, max_octave)
+    }
+
+    /// Re-clamp the octave to [`octave_bounds`] for `max_degree`, e.g. after
+    /// a root or scale change shifts where the high end lands.
+    fn clamp_octave(&mut self, max_degree: usize) {
+        let (min, max) = self.octave_bounds(max_degree);
+        self.octave = self.octave.clamp(min, max);
+    }
+
+    /// `max_degree` is the highest grid degree this `KeyMap` needs to stay
+    /// unclamped for (the pad grid's `rows * cols - 1`), so the octave
+    /// never saturates multiple pads onto the same MIDI note.
+    pub fn cycle_scale_next(&mut self, max_degree: usize) {
+        self.scale = self.scale.next();
+        self.clamp_octave(max_degree);
+    }
+
+    pub fn cycle_scale_prev(&mut self, max_degree: usize) {
+        self.scale = self.scale.prev();
+        self.clamp_octave(max_degree);
+    }
+
+    pub fn cycle_root(&mut self, delta: i8, max_degree: usize) {
+        self.root = ((self.root as i16 + delta as i16).rem_euclid(12)) as u8;
+        self.clamp_octave(max_degree);
+    }
+
+    pub fn shift_octave(&mut self, delta: i8, max_degree: usize) {
+        self.octave += delta;
+        self.clamp_octave(max_degree);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_root_matches_old_chromatic_grid() {
+        let key_map = KeyMap::default();
+        assert_eq!(key_map.note_for_degree(0), 36);
+        assert_eq!(key_map.note_for_degree(1), 37);
+    }
+
+    #[test]
+    fn test_major_scale_wraps_into_next_octave() {
+        let key_map = KeyMap {
+            scale: Scale::Major,
+            root: 0,
+            octave: 0,
+        };
+        assert_eq!(key_map.note_for_degree(0), 36);
+        assert_eq!(key_map.note_for_degree(6), 47); // 7th scale degree (B)
+        assert_eq!(key_map.note_for_degree(7), 48); // wraps: root an octave up
+    }
+
+    #[test]
+    fn test_note_name() {
+        assert_eq!(note_name(60), "C4");
+        assert_eq!(note_name(61), "C#4");
+    }
+
+    #[test]
+    fn test_octave_clamp_keeps_full_grid_distinct_at_max_octave() {
+        // 3x8 grid: degrees 0..=23. Major scale, root C used to saturate
+        // degrees 18-23 onto note 127 once octave reached +5.
+        const MAX_DEGREE: usize = 23;
+
+        let mut key_map = KeyMap {
+            scale: Scale::Major,
+            root: 0,
+            octave: 0,
+        };
+        for _ in 0..10 {
+            key_map.shift_octave(1, MAX_DEGREE);
+        }
+        assert_eq!(key_map.octave, 4, "old fixed ceiling of 5 let note_for_degree saturate");
+
+        let notes: Vec<u8> = (0..=MAX_DEGREE).map(|d| key_map.note_for_degree(d)).collect();
+        assert!(notes.iter().all(|&n| n <= 127));
+        let mut distinct = notes.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert_eq!(
+            distinct.len(),
+            notes.len(),
+            "every pad must map to a distinct note at the max octave"
+        );
+    }
+
+    #[test]
+    fn test_octave_clamp_keeps_full_grid_distinct_at_min_octave() {
+        const MAX_DEGREE: usize = 23;
+
+        let mut key_map = KeyMap {
+            scale: Scale::Major,
+            root: 0,
+            octave: 0,
+        };
+        for _ in 0..10 {
+            key_map.shift_octave(-1, MAX_DEGREE);
+        }
+        assert_eq!(key_map.octave, -3);
+
+        let notes: Vec<u8> = (0..=MAX_DEGREE).map(|d| key_map.note_for_degree(d)).collect();
+        let mut distinct = notes.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert_eq!(distinct.len(), notes.len());
+    }
+
+    #[test]
+    fn test_octave_clamp_tightens_for_roots_with_less_headroom() {
+        // Same grid, but a root near the top of the pitch-class range leaves
+        // even less headroom before 127, so the safe ceiling should drop
+        // further below the old fixed bound of 5.
+        const MAX_DEGREE: usize = 23;
+
+        let mut key_map = KeyMap {
+            scale: Scale::Major,
+            root: 11,
+            octave: 0,
+        };
+        for _ in 0..10 {
+            key_map.shift_octave(1, MAX_DEGREE);
+        }
+        let notes: Vec<u8> = (0..=MAX_DEGREE).map(|d| key_map.note_for_degree(d)).collect();
+        assert!(notes.iter().all(|&n| n <= 127));
+        let mut distinct = notes.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert_eq!(distinct.len(), notes.len());
+    }
+}
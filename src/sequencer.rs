@@ -0,0 +1,205 @@
+// a looping step sequencer driven by a background tempo clock
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Receiver},
+};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of steps in every pad's pattern.
+pub const PATTERN_LENGTH: usize = 16;
+
+/// One step of a pad's pattern: whether it fires at all, and the
+/// probability it actually does on any given pass (rolled fresh each time
+/// the playhead reaches it, so patterns can evolve instead of repeating
+/// identically).
+#[derive(Debug, Clone, Copy)]
+pub struct Step {
+    pub active: bool,
+    pub probability: f32,
+}
+
+impl Default for Step {
+    fn default() -> Self {
+        Self {
+            active: false,
+            probability: 1.0,
+        }
+    }
+}
+
+/// The set of pads to stop and start on a single tick of the clock.
+pub struct SequencerTick {
+    pub note_offs: Vec<usize>,
+    pub note_ons: Vec<usize>,
+}
+
+/// Records pad hits into per-pad step patterns and plays them back in a
+/// loop, driven by a background thread that ticks at the configured tempo.
+#[derive(Debug)]
+pub struct Sequencer {
+    pub bpm: f32,
+    pub steps_per_beat: u32,
+    pub recording: bool,
+    pub playhead: usize,
+    pub patterns: Vec<[Step; PATTERN_LENGTH]>,
+
+    running: Option<Arc<AtomicBool>>,
+    tick_rx: Option<Receiver<()>>,
+    last_triggered: Vec<usize>,
+    rng_state: u64,
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Sequencer {
+    pub fn new(pads: usize) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() as u64)
+            .unwrap_or(1);
+
+        Self {
+            bpm: 120.0,
+            steps_per_beat: 4,
+            recording: false,
+            playhead: 0,
+            patterns: vec![[Step::default(); PATTERN_LENGTH]; pads],
+            running: None,
+            tick_rx: None,
+            last_triggered: Vec::new(),
+            rng_state: seed | 1,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Start the tempo clock, if it isn't already running.
+    pub fn play(&mut self) {
+        if self.running.is_some() {
+            return;
+        }
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let step_duration =
+            Duration::from_secs_f32(60.0 / self.bpm / self.steps_per_beat as f32);
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            while thread_running.load(Ordering::Relaxed) {
+                thread::sleep(step_duration);
+                if tx.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        self.running = Some(running);
+        self.tick_rx = Some(rx);
+    }
+
+    /// Stop the tempo clock and silence anything still sounding from it.
+    pub fn stop(&mut self) -> Vec<usize> {
+        if let Some(running) = self.running.take() {
+            running.store(false, Ordering::Relaxed);
+        }
+        self.tick_rx = None;
+        self.playhead = 0;
+        std::mem::take(&mut self.last_triggered)
+    }
+
+    /// Erase every pad's pattern.
+    pub fn clear(&mut self) {
+        for pattern in &mut self.patterns {
+            *pattern = [Step::default(); PATTERN_LENGTH];
+        }
+    }
+
+    /// While recording, mark `pad`'s current step as active.
+    pub fn record(&mut self, pad: usize) {
+        if !self.recording {
+            return;
+        }
+        let step = &mut self.patterns[pad][self.playhead];
+        step.active = true;
+        step.probability = 1.0;
+    }
+
+    /// Drain a pending clock tick, if any: releases the pads triggered on
+    /// the previous step and rolls the pads that fire on this one.
+    pub fn poll(&mut self) -> Option<SequencerTick> {
+        self.tick_rx.as_ref()?.try_recv().ok()?;
+
+        let note_offs = std::mem::take(&mut self.last_triggered);
+
+        let note_ons: Vec<usize> = (0..self.patterns.len())
+            .filter(|&pad| {
+                let step = self.patterns[pad][self.playhead];
+                step.active && self.roll() < step.probability
+            })
+            .collect();
+
+        self.last_triggered = note_ons.clone();
+        self.playhead = (self.playhead + 1) % PATTERN_LENGTH;
+
+        Some(SequencerTick {
+            note_offs,
+            note_ons,
+        })
+    }
+
+    /// xorshift64 - enough randomness for a gig-bag step sequencer.
+    fn roll(&mut self) -> f32 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 7;
+        self.rng_state ^= self.rng_state << 17;
+        (self.rng_state >> 40) as f32 / (1u32 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_marks_current_step() {
+        let mut seq = Sequencer::new(4);
+        seq.recording = true;
+        seq.record(2);
+        assert!(seq.patterns[2][0].active);
+    }
+
+    #[test]
+    fn test_record_ignored_when_not_recording() {
+        let mut seq = Sequencer::new(4);
+        seq.record(2);
+        assert!(!seq.patterns[2][0].active);
+    }
+
+    #[test]
+    fn test_clear_resets_every_pattern() {
+        let mut seq = Sequencer::new(4);
+        seq.recording = true;
+        seq.record(0);
+        seq.clear();
+        assert!(!seq.patterns[0][0].active);
+    }
+
+    #[test]
+    fn test_roll_stays_within_unit_range() {
+        let mut seq = Sequencer::new(1);
+        for _ in 0..100 {
+            let r = seq.roll();
+            assert!((0.0..1.0).contains(&r));
+        }
+    }
+}